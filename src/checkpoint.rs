@@ -0,0 +1,287 @@
+use crate::coinstats::{BlockCoinDelta, MuHash3072};
+use crate::script::ScriptTypeCounts;
+use crate::RawBlockMetrics;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Flush the checkpoint's write buffer to the file every this many appended
+/// rows, rather than on every block. The file is never `fsync`-ed.
+const FLUSH_INTERVAL: usize = 1000;
+
+/// One append-only line in the checkpoint file: the expensive, per-block
+/// scan result (block/undo reads, script classification, MuHash insert, and
+/// filter construction) for a single height, checkpointed as soon as it's
+/// computed rather than after a serial fold over the whole run. This is the
+/// work a crash actually loses time re-doing; the cheap coinstats fold that
+/// turns these into cumulative `BlockResult`s is always just recomputed from
+/// scratch over every scanned height, so it doesn't need to be checkpointed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ScanCheckpointRecord {
+    height: i32,
+    total_txs: u32,
+    total_inputs: u32,
+    mixed_tx_count: u32,
+    p2pk_count: u32,
+    p2pkh_count: u32,
+    p2sh_count: u32,
+    p2wpkh_count: u32,
+    p2wsh_count: u32,
+    p2tr_count: u32,
+    multisig_count: u32,
+    op_return_count: u32,
+    unknown_script_count: u32,
+    muhash_hex: String,
+    utxo_count_delta: i64,
+    total_amount_delta: i64,
+    total_unspendable_amount_delta: u64,
+    subsidy: u64,
+    filter_hex: Option<String>,
+}
+
+impl ScanCheckpointRecord {
+    fn from_raw(height: i32, metrics: &RawBlockMetrics) -> Self {
+        let counts = &metrics.script_counts;
+        let delta = &metrics.coin_delta;
+        Self {
+            height,
+            total_txs: metrics.total_txs,
+            total_inputs: metrics.total_inputs,
+            mixed_tx_count: metrics.mixed_tx_count,
+            p2pk_count: counts.p2pk,
+            p2pkh_count: counts.p2pkh,
+            p2sh_count: counts.p2sh,
+            p2wpkh_count: counts.p2wpkh,
+            p2wsh_count: counts.p2wsh,
+            p2tr_count: counts.p2tr,
+            multisig_count: counts.multisig,
+            op_return_count: counts.op_return,
+            unknown_script_count: counts.unknown,
+            muhash_hex: hex::encode(delta.muhash.to_bytes()),
+            utxo_count_delta: delta.utxo_count_delta,
+            total_amount_delta: delta.total_amount_delta,
+            total_unspendable_amount_delta: delta.total_unspendable_amount_delta,
+            subsidy: delta.subsidy,
+            filter_hex: metrics.filter.as_ref().map(hex::encode),
+        }
+    }
+
+    fn into_raw(self) -> RawBlockMetrics {
+        let script_counts = ScriptTypeCounts {
+            p2pk: self.p2pk_count,
+            p2pkh: self.p2pkh_count,
+            p2sh: self.p2sh_count,
+            p2wpkh: self.p2wpkh_count,
+            p2wsh: self.p2wsh_count,
+            p2tr: self.p2tr_count,
+            multisig: self.multisig_count,
+            op_return: self.op_return_count,
+            unknown: self.unknown_script_count,
+        };
+        let muhash = hex::decode(&self.muhash_hex)
+            .map(|bytes| MuHash3072::from_bytes(&bytes))
+            .unwrap_or_default();
+
+        RawBlockMetrics {
+            height: self.height,
+            total_txs: self.total_txs,
+            total_inputs: self.total_inputs,
+            mixed_tx_count: self.mixed_tx_count,
+            script_counts,
+            coin_delta: BlockCoinDelta {
+                muhash,
+                utxo_count_delta: self.utxo_count_delta,
+                total_amount_delta: self.total_amount_delta,
+                total_unspendable_amount_delta: self.total_unspendable_amount_delta,
+                subsidy: self.subsidy,
+            },
+            filter: self.filter_hex.and_then(|s| hex::decode(s).ok()),
+        }
+    }
+}
+
+/// Appends scanned blocks' raw results to a checkpoint file as soon as each
+/// one finishes, so a crashed scan only has to re-do the heights it hadn't
+/// reached yet. Safe to share across rayon worker threads: `append` takes
+/// `&self` and synchronizes internally.
+pub struct CheckpointWriter {
+    writer: Mutex<BufWriter<File>>,
+    since_flush: AtomicUsize,
+}
+
+impl CheckpointWriter {
+    pub fn open(path: &str) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            writer: Mutex::new(BufWriter::new(file)),
+            since_flush: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn append(&self, height: i32, metrics: &RawBlockMetrics) -> std::io::Result<()> {
+        let record = ScanCheckpointRecord::from_raw(height, metrics);
+        let mut writer = self.writer.lock().unwrap();
+        serde_json::to_writer(&mut *writer, &record)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        writer.write_all(b"\n")?;
+
+        if self.since_flush.fetch_add(1, Ordering::Relaxed) + 1 >= FLUSH_INTERVAL {
+            writer.flush()?;
+            self.since_flush.store(0, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    pub fn flush(&self) -> std::io::Result<()> {
+        self.writer.lock().unwrap().flush()
+    }
+}
+
+/// Reads a checkpoint file written by `CheckpointWriter`, if it exists,
+/// returning every height already scanned, keyed by height. Stops at the
+/// first line that fails to parse, treating everything before it as the
+/// durable, resumable prefix (a crash mid-write can leave a truncated last
+/// line).
+pub fn resume(path: &str) -> std::io::Result<HashMap<i32, RawBlockMetrics>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut completed = HashMap::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        let record: ScanCheckpointRecord = match serde_json::from_str(&line) {
+            Ok(record) => record,
+            Err(e) => {
+                warn!("Stopping checkpoint replay at a malformed line: {}", e);
+                break;
+            }
+        };
+        completed.insert(record.height, record.into_raw());
+    }
+
+    if !completed.is_empty() {
+        info!(
+            "Resuming from checkpoint {}: {} blocks already scanned",
+            path,
+            completed.len()
+        );
+    }
+
+    Ok(completed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coinstats::CoinData;
+    use crate::script::ScriptType;
+
+    fn sample_metrics(height: i32) -> RawBlockMetrics {
+        let mut script_counts = ScriptTypeCounts::default();
+        script_counts.record(ScriptType::P2wpkh);
+        script_counts.record(ScriptType::OpReturn);
+
+        let mut muhash = MuHash3072::new();
+        muhash.insert(&CoinData {
+            txid: [height as u8; 32],
+            vout: 0,
+            height,
+            is_coinbase: false,
+            amount: 5_000,
+            script_pubkey: vec![0x00, 0x14],
+        });
+
+        RawBlockMetrics {
+            height,
+            total_txs: 2,
+            total_inputs: 3,
+            mixed_tx_count: 1,
+            script_counts,
+            coin_delta: BlockCoinDelta {
+                muhash,
+                utxo_count_delta: 1,
+                total_amount_delta: 5_000,
+                total_unspendable_amount_delta: 0,
+                subsidy: 625_000_000,
+            },
+            filter: Some(vec![0xde, 0xad, 0xbe, 0xef]),
+        }
+    }
+
+    #[test]
+    fn scan_checkpoint_record_round_trips() {
+        let metrics = sample_metrics(100);
+        let record = ScanCheckpointRecord::from_raw(metrics.height, &metrics);
+        let restored = record.into_raw();
+
+        assert_eq!(restored.height, metrics.height);
+        assert_eq!(restored.total_txs, metrics.total_txs);
+        assert_eq!(restored.total_inputs, metrics.total_inputs);
+        assert_eq!(restored.mixed_tx_count, metrics.mixed_tx_count);
+        assert_eq!(restored.script_counts.p2wpkh, metrics.script_counts.p2wpkh);
+        assert_eq!(restored.script_counts.op_return, metrics.script_counts.op_return);
+        assert_eq!(
+            restored.coin_delta.utxo_count_delta,
+            metrics.coin_delta.utxo_count_delta
+        );
+        assert_eq!(restored.coin_delta.subsidy, metrics.coin_delta.subsidy);
+        assert_eq!(
+            restored.coin_delta.muhash.finalize_hex(),
+            metrics.coin_delta.muhash.finalize_hex()
+        );
+        assert_eq!(restored.filter, metrics.filter);
+    }
+
+    #[test]
+    fn scan_checkpoint_record_round_trips_without_filter() {
+        let mut metrics = sample_metrics(101);
+        metrics.filter = None;
+
+        let restored = ScanCheckpointRecord::from_raw(metrics.height, &metrics).into_raw();
+
+        assert_eq!(restored.filter, None);
+    }
+
+    #[test]
+    fn resume_stops_at_truncated_last_line() {
+        let path = std::env::temp_dir().join(format!(
+            "block_analyser_checkpoint_test_{}.ndjson",
+            std::process::id()
+        ));
+
+        let good = ScanCheckpointRecord::from_raw(100, &sample_metrics(100));
+        let mut contents = serde_json::to_string(&good).unwrap();
+        contents.push('\n');
+        contents.push_str("{\"height\": 101, this is not valid json");
+
+        std::fs::write(&path, &contents).unwrap();
+
+        let completed = resume(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(completed.len(), 1);
+        assert!(completed.contains_key(&100));
+    }
+
+    #[test]
+    fn resume_missing_file_returns_empty() {
+        let path = std::env::temp_dir().join(format!(
+            "block_analyser_checkpoint_test_missing_{}.ndjson",
+            std::process::id()
+        ));
+
+        let completed = resume(path.to_str().unwrap()).unwrap();
+
+        assert!(completed.is_empty());
+    }
+}
@@ -0,0 +1,252 @@
+use chacha20::cipher::{KeyIvInit, StreamCipher};
+use chacha20::ChaCha20;
+use num_bigint::BigUint;
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
+
+/// MuHash3072 operates over Z/nZ where n is the largest 3072-bit prime,
+/// matching Bitcoin Core's MuHash3072 (see `coinstatsindex`/`MuHash3072`).
+const MUHASH_MODULUS_BYTES: usize = 384; // 3072 bits
+
+fn modulus() -> &'static BigUint {
+    static MODULUS: OnceLock<BigUint> = OnceLock::new();
+    MODULUS.get_or_init(|| (BigUint::from(1u8) << 3072u32) - BigUint::from(1_103_717u32))
+}
+
+/// A coin as it is inserted into or removed from the rolling UTXO-set
+/// commitment: `(outpoint || height || coinbase-flag || amount || scriptPubKey)`.
+pub struct CoinData {
+    pub txid: [u8; 32],
+    pub vout: u32,
+    pub height: i32,
+    pub is_coinbase: bool,
+    pub amount: u64,
+    pub script_pubkey: Vec<u8>,
+}
+
+impl CoinData {
+    fn serialize(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(32 + 4 + 4 + 1 + 8 + self.script_pubkey.len());
+        buf.extend_from_slice(&self.txid);
+        buf.extend_from_slice(&self.vout.to_le_bytes());
+        buf.extend_from_slice(&self.height.to_le_bytes());
+        buf.push(self.is_coinbase as u8);
+        buf.extend_from_slice(&self.amount.to_le_bytes());
+        buf.extend_from_slice(&self.script_pubkey);
+        buf
+    }
+}
+
+/// Expands a coin's serialized data into a uniformly-distributed element of
+/// the MuHash3072 group by seeding a ChaCha20 keystream with its SHA256 hash.
+fn data_to_num3072(data: &[u8]) -> BigUint {
+    let seed = Sha256::digest(data);
+    let mut keystream = [0u8; MUHASH_MODULUS_BYTES];
+    let mut cipher = ChaCha20::new(seed.as_slice().into(), &[0u8; 12].into());
+    cipher.apply_keystream(&mut keystream);
+    BigUint::from_bytes_le(&keystream) % modulus()
+}
+
+/// An order-independent, incrementally-updatable hash of a set of coins.
+///
+/// Insertion and removal both reduce to multiplying (or dividing) the
+/// running accumulator by the coin's group element, so two accumulators
+/// covering disjoint block ranges can be combined with `combine` regardless
+/// of the order in which their blocks were processed.
+#[derive(Debug, Clone)]
+pub struct MuHash3072 {
+    acc: BigUint,
+}
+
+impl Default for MuHash3072 {
+    fn default() -> Self {
+        Self { acc: BigUint::from(1u8) }
+    }
+}
+
+impl MuHash3072 {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, coin: &CoinData) {
+        let elem = data_to_num3072(&coin.serialize());
+        self.acc = (&self.acc * elem) % modulus();
+    }
+
+    pub fn remove(&mut self, coin: &CoinData) {
+        let elem = data_to_num3072(&coin.serialize());
+        let inverse = elem.modpow(&(modulus() - BigUint::from(2u8)), modulus());
+        self.acc = (&self.acc * inverse) % modulus();
+    }
+
+    pub fn combine(&mut self, other: &MuHash3072) {
+        self.acc = (&self.acc * &other.acc) % modulus();
+    }
+
+    /// `SHA256(serialize(accumulator))`, the UTXO-set hash at this point.
+    pub fn finalize_hex(&self) -> String {
+        hex::encode(Sha256::digest(self.to_bytes()))
+    }
+
+    /// Serializes the accumulator itself (not its hash) so a checkpoint can
+    /// resume the rolling commitment exactly where it left off.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.acc.to_bytes_le();
+        bytes.resize(MUHASH_MODULUS_BYTES, 0);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self { acc: BigUint::from_bytes_le(bytes) }
+    }
+}
+
+/// The set-level effect of a single block: its own coin insertions and
+/// removals, combined into one partial MuHash accumulator plus the
+/// corresponding deltas to the aggregate coin statistics. Computed
+/// independently per block so blocks can be processed in parallel and
+/// folded into a running total afterwards.
+#[derive(Debug, Clone)]
+pub struct BlockCoinDelta {
+    pub muhash: MuHash3072,
+    pub utxo_count_delta: i64,
+    pub total_amount_delta: i64,
+    pub total_unspendable_amount_delta: u64,
+    /// Sum of this block's coinbase transaction's output amounts, i.e. the
+    /// subsidy plus fees actually claimed (not the nominal halving amount,
+    /// which miners are free to under-claim).
+    pub subsidy: u64,
+}
+
+impl BlockCoinDelta {
+    pub fn from_coins(
+        created: &[CoinData],
+        spent: &[CoinData],
+        is_unspendable: impl Fn(&[u8]) -> bool,
+    ) -> Self {
+        let mut muhash = MuHash3072::new();
+        let mut utxo_count_delta = 0i64;
+        let mut total_amount_delta = 0i64;
+        let mut total_unspendable_amount_delta = 0u64;
+        let mut subsidy = 0u64;
+
+        for coin in created {
+            if coin.is_coinbase {
+                subsidy += coin.amount;
+            }
+            if is_unspendable(&coin.script_pubkey) {
+                total_unspendable_amount_delta += coin.amount;
+                continue;
+            }
+            muhash.insert(coin);
+            utxo_count_delta += 1;
+            total_amount_delta += coin.amount as i64;
+        }
+        for coin in spent {
+            muhash.remove(coin);
+            utxo_count_delta -= 1;
+            total_amount_delta -= coin.amount as i64;
+        }
+
+        Self {
+            muhash,
+            utxo_count_delta,
+            total_amount_delta,
+            total_unspendable_amount_delta,
+            subsidy,
+        }
+    }
+}
+
+/// A running, rolling UTXO-set commitment plus aggregate coin statistics,
+/// updated one block at a time via `apply`.
+#[derive(Debug, Clone, Default)]
+pub struct CoinStatsAccumulator {
+    muhash: MuHash3072,
+    pub utxo_count: u64,
+    pub total_amount: u64,
+    pub total_unspendable_amount: u64,
+    /// Cumulative sum of every block's actual coinbase claim (see
+    /// `BlockCoinDelta::subsidy`).
+    pub total_subsidy: u64,
+}
+
+/// A snapshot of the running coin statistics after processing one block.
+pub struct CoinStatsSnapshot {
+    pub utxo_set_hash: String,
+    pub utxo_count: u64,
+    pub total_amount: u64,
+    pub total_unspendable_amount: u64,
+    pub total_subsidy: u64,
+}
+
+impl CoinStatsAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn apply(&mut self, delta: &BlockCoinDelta) -> CoinStatsSnapshot {
+        self.muhash.combine(&delta.muhash);
+        self.utxo_count = self.utxo_count.saturating_add_signed(delta.utxo_count_delta);
+        self.total_amount = self.total_amount.saturating_add_signed(delta.total_amount_delta);
+        self.total_unspendable_amount += delta.total_unspendable_amount_delta;
+        self.total_subsidy += delta.subsidy;
+
+        CoinStatsSnapshot {
+            utxo_set_hash: self.muhash.finalize_hex(),
+            utxo_count: self.utxo_count,
+            total_amount: self.total_amount,
+            total_unspendable_amount: self.total_unspendable_amount,
+            total_subsidy: self.total_subsidy,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_coin() -> CoinData {
+        CoinData {
+            txid: [7u8; 32],
+            vout: 1,
+            height: 500_000,
+            is_coinbase: false,
+            amount: 123_456_789,
+            script_pubkey: vec![0x76, 0xa9, 0x14, 0x01, 0x02, 0x03, 0x88, 0xac],
+        }
+    }
+
+    #[test]
+    fn insert_then_remove_is_identity() {
+        let coin = sample_coin();
+        let mut muhash = MuHash3072::new();
+        let before = muhash.finalize_hex();
+
+        muhash.insert(&coin);
+        assert_ne!(muhash.finalize_hex(), before);
+
+        muhash.remove(&coin);
+        assert_eq!(muhash.finalize_hex(), before);
+    }
+
+    #[test]
+    fn combine_is_commutative_with_insert_order() {
+        let a = sample_coin();
+        let mut b = sample_coin();
+        b.vout = 2;
+
+        let mut inserted_a_then_b = MuHash3072::new();
+        inserted_a_then_b.insert(&a);
+        inserted_a_then_b.insert(&b);
+
+        let mut b_hash = MuHash3072::new();
+        b_hash.insert(&b);
+        let mut a_then_combined = MuHash3072::new();
+        a_then_combined.insert(&a);
+        a_then_combined.combine(&b_hash);
+
+        assert_eq!(inserted_a_then_b.finalize_hex(), a_then_combined.finalize_hex());
+    }
+}
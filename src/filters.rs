@@ -0,0 +1,224 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use siphasher::sip::SipHasher24;
+use std::collections::HashSet;
+use std::fs::File;
+use std::hash::Hasher;
+use std::io::{BufWriter, Write};
+
+/// BIP158 basic filter parameters.
+const P: u32 = 19;
+const M: u64 = 784_931;
+
+/// Maps a scriptPubKey to a 64-bit value, keyed by the first 16 bytes of the
+/// block hash, per BIP158's `hashToRange`.
+fn siphash(block_hash: &[u8; 32], data: &[u8]) -> u64 {
+    let k0 = u64::from_le_bytes(block_hash[0..8].try_into().unwrap());
+    let k1 = u64::from_le_bytes(block_hash[8..16].try_into().unwrap());
+    let mut hasher = SipHasher24::new_with_keys(k0, k1);
+    hasher.write(data);
+    hasher.finish()
+}
+
+fn hash_to_range(hash: u64, f: u64) -> u64 {
+    ((hash as u128 * f as u128) >> 64) as u64
+}
+
+/// Minimal MSB-first bit writer used for Golomb-Rice coding.
+struct BitWriter {
+    bytes: Vec<u8>,
+    bit_pos: u8,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self { bytes: vec![0], bit_pos: 0 }
+    }
+
+    fn write_bit(&mut self, bit: bool) {
+        if bit {
+            *self.bytes.last_mut().unwrap() |= 0x80 >> self.bit_pos;
+        }
+        self.bit_pos += 1;
+        if self.bit_pos == 8 {
+            self.bit_pos = 0;
+            self.bytes.push(0);
+        }
+    }
+
+    fn write_bits(&mut self, value: u64, n_bits: u32) {
+        for i in (0..n_bits).rev() {
+            self.write_bit((value >> i) & 1 == 1);
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bit_pos == 0 {
+            self.bytes.pop();
+        }
+        self.bytes
+    }
+}
+
+/// Golomb-Rice encodes sorted, delta-encoded hashed set values with
+/// parameter `P`.
+fn golomb_rice_encode(deltas: &[u64]) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+    for &delta in deltas {
+        let quotient = delta >> P;
+        for _ in 0..quotient {
+            writer.write_bit(true);
+        }
+        writer.write_bit(false);
+        writer.write_bits(delta & ((1 << P) - 1), P);
+    }
+    writer.finish()
+}
+
+fn write_compact_size(buf: &mut Vec<u8>, n: u64) {
+    if n < 253 {
+        buf.push(n as u8);
+    } else if n <= 0xffff {
+        buf.push(253);
+        buf.extend_from_slice(&(n as u16).to_le_bytes());
+    } else if n <= 0xffff_ffff {
+        buf.push(254);
+        buf.extend_from_slice(&(n as u32).to_le_bytes());
+    } else {
+        buf.push(255);
+        buf.extend_from_slice(&n.to_le_bytes());
+    }
+}
+
+/// Builds a BIP158 basic block filter (the N prefix plus the Golomb-Coded
+/// Set) from the distinct scriptPubKeys observed in a block: its prevouts'
+/// scriptPubKeys (from undo data) and its own non-OP_RETURN output
+/// scriptPubKeys.
+pub fn build_filter(block_hash: &[u8; 32], scripts: impl Iterator<Item = Vec<u8>>) -> Vec<u8> {
+    let distinct: HashSet<Vec<u8>> = scripts.collect();
+    let n = distinct.len() as u64;
+    let f = n * M;
+
+    let mut hashed: Vec<u64> = distinct
+        .iter()
+        .map(|script| hash_to_range(siphash(block_hash, script), f))
+        .collect();
+    hashed.sort_unstable();
+
+    let mut deltas = Vec::with_capacity(hashed.len());
+    let mut prev = 0u64;
+    for value in hashed {
+        deltas.push(value - prev);
+        prev = value;
+    }
+
+    let mut out = Vec::new();
+    write_compact_size(&mut out, n);
+    out.extend_from_slice(&golomb_rice_encode(&deltas));
+    out
+}
+
+/// `SHA256(SHA256(filter))`, the filter hash used in the header chain.
+pub fn filter_hash(filter: &[u8]) -> [u8; 32] {
+    Sha256::digest(Sha256::digest(filter)).into()
+}
+
+/// Next link in the filter header chain: `SHA256d(filter_hash || prev_header)`.
+pub fn next_filter_header(filter: &[u8], prev_header: [u8; 32]) -> [u8; 32] {
+    let hash = filter_hash(filter);
+    let mut data = Vec::with_capacity(64);
+    data.extend_from_slice(&hash);
+    data.extend_from_slice(&prev_header);
+    Sha256::digest(Sha256::digest(data)).into()
+}
+
+/// One block's filter and its chained header, as written to the
+/// `--emit-filters` sidecar file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FilterRecord {
+    pub height: i32,
+    pub filter: String,
+    /// `SHA256d(filter_hash || prev_header)`, chained from an all-zero
+    /// predecessor at the start of the scanned range. Only matches the real
+    /// BIP157 filter header chain (and is therefore only comparable against
+    /// network/Core filter headers) when the scan started at height 0; for
+    /// any other `--start` this is a range-relative chain.
+    pub header: String,
+}
+
+pub fn write_filters_sidecar(records: &[FilterRecord], filename: &str) -> std::io::Result<()> {
+    info!("Writing {} block filters to {}", records.len(), filename);
+    let file = File::create(filename)?;
+    let mut writer = BufWriter::new(file);
+    for record in records {
+        serde_json::to_writer(&mut writer, record)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        writer.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads back a Golomb-Rice stream produced by `golomb_rice_encode`, the
+    /// inverse of its unary-quotient/`P`-bit-remainder encoding.
+    fn golomb_rice_decode(bytes: &[u8], n: usize) -> Vec<u64> {
+        let bits: Vec<bool> = bytes
+            .iter()
+            .flat_map(|byte| (0..8).map(move |i| (byte >> (7 - i)) & 1 == 1))
+            .collect();
+        let mut pos = 0;
+        let mut out = Vec::with_capacity(n);
+        for _ in 0..n {
+            let mut quotient = 0u64;
+            while bits[pos] {
+                quotient += 1;
+                pos += 1;
+            }
+            pos += 1; // skip the terminating 0 bit
+            let mut remainder = 0u64;
+            for _ in 0..P {
+                remainder = (remainder << 1) | bits[pos] as u64;
+                pos += 1;
+            }
+            out.push((quotient << P) | remainder);
+        }
+        out
+    }
+
+    #[test]
+    fn golomb_rice_round_trips_known_deltas() {
+        let deltas = vec![0u64, 1, 500_000, (1 << P) - 1, 1 << P, 3 * (1 << P) + 42];
+        let encoded = golomb_rice_encode(&deltas);
+        assert_eq!(golomb_rice_decode(&encoded, deltas.len()), deltas);
+    }
+
+    #[test]
+    fn write_compact_size_matches_bitcoin_varint() {
+        let mut buf = Vec::new();
+        write_compact_size(&mut buf, 252);
+        assert_eq!(buf, vec![252]);
+
+        let mut buf = Vec::new();
+        write_compact_size(&mut buf, 253);
+        assert_eq!(buf, vec![253, 253, 0]);
+
+        let mut buf = Vec::new();
+        write_compact_size(&mut buf, 0x10000);
+        assert_eq!(buf, vec![254, 0, 0, 1, 0]);
+    }
+
+    #[test]
+    fn build_filter_is_deterministic_and_order_independent() {
+        let block_hash = [9u8; 32];
+        let scripts = vec![vec![0xaa, 0xbb], vec![0xcc, 0xdd, 0xee]];
+
+        let filter_a = build_filter(&block_hash, scripts.clone().into_iter());
+        let filter_b = build_filter(&block_hash, scripts.into_iter().rev());
+
+        assert_eq!(filter_a, filter_b);
+    }
+}
@@ -1,28 +1,28 @@
 use bitcoinkernel::{
     BlockManagerOptions, ChainType, ChainstateLoadOptions, ChainstateManager,
-    ChainstateManagerOptions, ScriptPubkey,
+    ChainstateManagerOptions,
 };
 use clap::Parser;
 use log::{error, info, warn};
 use rayon::prelude::*;
-use std::fs::File;
-use std::io::Write;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::sync::Mutex;
 use std::time::Instant;
 
+mod checkpoint;
+mod coinstats;
+mod filters;
 mod kernel;
+mod output;
+mod script;
+mod stats;
+use crate::coinstats::{BlockCoinDelta, CoinData, CoinStatsAccumulator};
+use crate::filters::{build_filter, next_filter_header, write_filters_sidecar, FilterRecord};
 use crate::kernel::{create_context, setup_logging};
-
-#[derive(Debug, Clone)]
-struct BlockResult {
-    height: i32,
-    total_txs: u32,
-    total_inputs: u32,
-    mixed_tx_count: u32,
-    schnorr_sigs: u32,
-    non_schnorr_sigs: u32,
-}
+use crate::output::{write_results, BlockResult, OutputFormat};
+use crate::script::{classify_script, classify_script_bytes, ScriptType, ScriptTypeCounts};
+use crate::stats::BlockStats;
 
 /// A simple CLI tool
 #[derive(Parser, Debug)]
@@ -40,78 +40,156 @@ struct Args {
     /// End block height
     #[arg(long)]
     end: i32,
-    /// Output CSV file
+    /// Output file
     #[arg(long, default_value = "block_stats.csv")]
     output: String,
+    /// Output format
+    #[arg(long, value_enum, default_value = "csv")]
+    format: OutputFormat,
+    /// Emit BIP158 compact block filters to a sidecar file
+    #[arg(long)]
+    emit_filters: bool,
+    /// Sidecar file for block filters, used with --emit-filters
+    #[arg(long, default_value = "block_filters.ndjson")]
+    filters_output: String,
+    /// Periodically checkpoint each block's scan result to this file and
+    /// resume from it on startup, skipping already-scanned heights
+    #[arg(long)]
+    checkpoint: Option<String>,
 }
 
-/// Check if a script public key is Pay-to-Taproot (P2TR)
-fn is_p2tr(spk: ScriptPubkey) -> bool {
-    let spk_bytes = spk.get();
-    if spk_bytes.len() != 34 {
-        return false;
-    }
-    // OP_1 (0x51) OP_PUSHBYTES_32 (0x20) <32 bytes>
-    spk_bytes[0] == 0x51 && spk_bytes[1] == 0x20
+/// Per-block metrics that still need to be folded into the running
+/// coinstats accumulator before they become a final `BlockResult`.
+struct RawBlockMetrics {
+    height: i32,
+    total_txs: u32,
+    total_inputs: u32,
+    mixed_tx_count: u32,
+    script_counts: ScriptTypeCounts,
+    coin_delta: BlockCoinDelta,
+    /// Raw BIP158 basic filter bytes, present only when `--emit-filters` is set.
+    filter: Option<Vec<u8>>,
 }
 
-fn write_results_to_csv(results: &[BlockResult], filename: &str) -> std::io::Result<()> {
-    info!("Writing results to CSV file: {}", filename);
-    let start_time = Instant::now();
-
-    let mut file = File::create(filename)?;
-
-    // Write header
-    writeln!(
-        file,
-        "height,total_txs,total_inputs,mixed_tx_count,schnorr_sigs,non_schnorr_sigs"
-    )?;
-
-    // Write data
-    for result in results {
-        writeln!(
-            file,
-            "{},{},{},{},{},{}",
-            result.height,
-            result.total_txs,
-            result.total_inputs,
-            result.mixed_tx_count,
-            result.schnorr_sigs,
-            result.non_schnorr_sigs
-        )?;
+impl RawBlockMetrics {
+    fn to_stats(&self) -> BlockStats {
+        BlockStats {
+            blocks_processed: 1,
+            total_txs: self.total_txs as u64,
+            total_inputs: self.total_inputs as u64,
+            mixed_tx_count: self.mixed_tx_count as u64,
+            p2pk_count: self.script_counts.p2pk as u64,
+            p2pkh_count: self.script_counts.p2pkh as u64,
+            p2sh_count: self.script_counts.p2sh as u64,
+            p2wpkh_count: self.script_counts.p2wpkh as u64,
+            p2wsh_count: self.script_counts.p2wsh as u64,
+            p2tr_count: self.script_counts.p2tr as u64,
+            multisig_count: self.script_counts.multisig as u64,
+            op_return_count: self.script_counts.op_return as u64,
+            unknown_script_count: self.script_counts.unknown as u64,
+            utxo_count_delta: self.coin_delta.utxo_count_delta,
+            total_amount_delta: self.coin_delta.total_amount_delta,
+            total_unspendable_amount: self.coin_delta.total_unspendable_amount_delta,
+            total_subsidy: self.coin_delta.subsidy,
+        }
     }
-
-    info!(
-        "CSV file written successfully in {:.2}s",
-        start_time.elapsed().as_secs_f32()
-    );
-    Ok(())
 }
 
-fn process_blocks(chainman: &ChainstateManager, start: i32, end: i32) -> Vec<BlockResult> {
+fn process_blocks(
+    chainman: &ChainstateManager,
+    start: i32,
+    end: i32,
+    emit_filters: bool,
+    checkpoint_path: Option<&str>,
+) -> (Vec<BlockResult>, Vec<FilterRecord>) {
     info!("Starting block processing from height {} to {}", start, end);
     let start_time = Instant::now();
 
-    // Create a vector of block heights to process
-    let block_heights: Vec<i32> = (start..=end).collect();
-    let results = Arc::new(Mutex::new(Vec::new()));
-    let progress = Arc::new(Mutex::new((0, block_heights.len())));
+    // Heights whose expensive scan (block/undo reads, script classification,
+    // MuHash insert, filter construction) a prior, possibly crashed, run
+    // already checkpointed: these are reused as-is instead of re-scanned.
+    // The checkpoint file may cover a wider range than this run asked for
+    // (e.g. a narrower or shifted `--start`/`--end` against an old
+    // checkpoint), so only heights inside `start..=end` are kept.
+    let already_scanned: HashMap<i32, RawBlockMetrics> = match checkpoint_path
+        .map(checkpoint::resume)
+        .transpose()
+    {
+        Ok(scanned) => scanned
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(height, _)| (start..=end).contains(height))
+            .collect(),
+        Err(e) => {
+            warn!("Failed to read checkpoint, starting from scratch: {}", e);
+            HashMap::new()
+        }
+    };
+
+    let block_heights: Vec<i32> = (start..=end).filter(|h| !already_scanned.contains_key(h)).collect();
+    let total_heights = (end - start + 1).max(0) as usize;
+    let progress = AtomicUsize::new(already_scanned.len());
+
+    let checkpoint_writer = checkpoint_path
+        .map(checkpoint::CheckpointWriter::open)
+        .transpose()
+        .unwrap_or_else(|e| {
+            warn!("Failed to open checkpoint file: {}", e);
+            None
+        });
+
+    // Process blocks in parallel, folding straight into a Vec with no
+    // shared-lock contention on the hot path. Each height's result is
+    // checkpointed (if requested) as soon as it's computed, so a crash
+    // during this expensive scan only loses the heights still in flight.
+    let mut raw_results: Vec<RawBlockMetrics> = block_heights
+        .par_iter()
+        .filter_map(|height| {
+            let mut mixed_tx_count = 0;
+            let mut script_counts = ScriptTypeCounts::default();
+            let mut total_txs = 0;
+            let mut total_inputs = 0;
+            let mut created_coins = Vec::new();
+            let mut spent_coins = Vec::new();
+
+            let block_index = match chainman.get_block_index_by_height(*height) {
+                Ok(block_index) => block_index,
+                Err(_) => {
+                    warn!("Failed to get block index for height {}", height);
+                    return None;
+                }
+            };
 
-    // Process blocks in parallel
-    block_heights.par_iter().for_each(|height| {
-        let mut mixed_tx_count = 0;
-        let mut schnorr_count = 0;
-        let mut non_schnorr_count = 0;
-        let mut total_txs = 0;
-        let mut total_inputs = 0;
+            if let Ok(block) = chainman.read_block_data(&block_index) {
+                for i in 0..block.get_transaction_count() {
+                    if let Ok(tx) = block.get_transaction(i.try_into().unwrap()) {
+                        let is_coinbase = tx.is_coinbase();
+                        for k in 0..tx.get_output_count() {
+                            if let Ok(output) = tx.get_output(k.try_into().unwrap()) {
+                                let script_pubkey = output.get_script_pubkey().get().to_vec();
+                                script_counts.record(classify_script_bytes(&script_pubkey));
+                                created_coins.push(CoinData {
+                                    txid: tx.txid(),
+                                    vout: k as u32,
+                                    height: *height,
+                                    is_coinbase,
+                                    amount: output.get_value(),
+                                    script_pubkey,
+                                });
+                            }
+                        }
+                    }
+                }
+            } else {
+                warn!("Failed to read block data for block at height {}", height);
+            }
 
-        if let Ok(block_index) = chainman.get_block_index_by_height(*height) {
             if let Ok(undo) = chainman.read_undo_data(&block_index) {
                 // Process each transaction
                 total_txs = undo.n_tx_undo;
                 for i in 0..undo.n_tx_undo {
-                    let mut has_schnorr = false;
-                    let mut has_non_schnorr = false;
+                    let mut has_p2tr = false;
+                    let mut has_non_p2tr = false;
 
                     let transaction_undo_size =
                         undo.get_transaction_undo_size(i.try_into().unwrap());
@@ -121,17 +199,29 @@ fn process_blocks(chainman: &ChainstateManager, start: i32, end: i32) -> Vec<Blo
                         if let Ok(prevout) =
                             undo.get_prevout_by_index(i.try_into().unwrap(), j.try_into().unwrap())
                         {
-                            if is_p2tr(prevout.get_script_pubkey()) {
-                                has_schnorr = true;
-                                schnorr_count += 1;
+                            // Only used to flag mixed-input transactions below;
+                            // script_counts tallies newly created outputs instead
+                            // (see the output loop above), to measure adoption of
+                            // new address types rather than what's being spent.
+                            let script_type = classify_script(&prevout.get_script_pubkey());
+                            if script_type == ScriptType::P2tr {
+                                has_p2tr = true;
                             } else {
-                                has_non_schnorr = true;
-                                non_schnorr_count += 1;
+                                has_non_p2tr = true;
                             }
+
+                            spent_coins.push(CoinData {
+                                txid: prevout.get_outpoint_txid(),
+                                vout: prevout.get_outpoint_vout(),
+                                height: prevout.get_height(),
+                                is_coinbase: prevout.is_coinbase(),
+                                amount: prevout.get_amount(),
+                                script_pubkey: prevout.get_script_pubkey().get().to_vec(),
+                            });
                         }
                     }
 
-                    if has_schnorr && has_non_schnorr {
+                    if has_p2tr && has_non_p2tr {
                         mixed_tx_count += 1;
                     }
                 }
@@ -139,47 +229,156 @@ fn process_blocks(chainman: &ChainstateManager, start: i32, end: i32) -> Vec<Blo
                 warn!("Failed to read undo data for block at height {}", height);
             }
 
-            // Store the results for this block
-            if let Ok(mut results_guard) = results.lock() {
-                results_guard.push(BlockResult {
-                    height: *height,
-                    total_txs: total_txs as u32,
-                    total_inputs: total_inputs as u32,
-                    mixed_tx_count,
-                    schnorr_sigs: schnorr_count,
-                    non_schnorr_sigs: non_schnorr_count,
-                });
+            let coin_delta = BlockCoinDelta::from_coins(&created_coins, &spent_coins, |spk| {
+                classify_script_bytes(spk) == ScriptType::OpReturn
+            });
+
+            let filter = if emit_filters {
+                let block_hash = block_index.get_hash();
+                let scripts = created_coins
+                    .iter()
+                    .filter(|coin| classify_script_bytes(&coin.script_pubkey) != ScriptType::OpReturn)
+                    .chain(spent_coins.iter())
+                    .map(|coin| coin.script_pubkey.clone());
+                Some(build_filter(&block_hash, scripts))
+            } else {
+                None
+            };
+
+            let processed = progress.fetch_add(1, Ordering::Relaxed) + 1;
+            if processed % 10000 == 0 || processed == total_heights {
+                info!(
+                    "Processed {}/{} blocks ({:.1}%) in {:.2}s",
+                    processed,
+                    total_heights,
+                    (processed as f32 / total_heights as f32) * 100.0,
+                    start_time.elapsed().as_secs_f32()
+                );
             }
 
-            // Update and log progress
-            if let Ok(mut progress_guard) = progress.lock() {
-                progress_guard.0 += 1;
-                if progress_guard.0 % 10000 == 0 || progress_guard.0 == progress_guard.1 {
-                    info!(
-                        "Processed {}/{} blocks ({:.1}%) in {:.2}s",
-                        progress_guard.0,
-                        progress_guard.1,
-                        (progress_guard.0 as f32 / progress_guard.1 as f32) * 100.0,
-                        start_time.elapsed().as_secs_f32()
-                    );
+            let metrics = RawBlockMetrics {
+                height: *height,
+                total_txs: total_txs as u32,
+                total_inputs: total_inputs as u32,
+                mixed_tx_count,
+                script_counts,
+                coin_delta,
+                filter,
+            };
+
+            if let Some(writer) = &checkpoint_writer {
+                if let Err(e) = writer.append(*height, &metrics) {
+                    warn!("Failed to checkpoint height {}: {}", height, e);
                 }
             }
-        } else {
-            warn!("Failed to get block index for height {}", height);
+
+            Some(metrics)
+        })
+        .collect();
+
+    raw_results.extend(already_scanned.into_values());
+
+    if let Some(writer) = &checkpoint_writer {
+        if let Err(e) = writer.flush() {
+            warn!("Failed to flush checkpoint file: {}", e);
+        }
+    }
+
+    // Range-wide totals, folded via `reduce` so no serial pass is needed to
+    // know the aggregate coin stats. Deliberately excludes the MuHash
+    // commitment itself, which is already folded serially below (and whose
+    // per-row cumulative value that serial pass produces anyway).
+    let range_stats = raw_results
+        .par_iter()
+        .map(RawBlockMetrics::to_stats)
+        .reduce(BlockStats::default, BlockStats::combine);
+    info!(
+        "Range totals: {} blocks, {} txs, net {} UTXOs, {} sats total, {} sats subsidy",
+        range_stats.blocks_processed,
+        range_stats.total_txs,
+        range_stats.utxo_count_delta,
+        range_stats.total_amount_delta,
+        range_stats.total_subsidy,
+    );
+
+    // Sort by height, then fold the per-block coin deltas into a running
+    // coinstats accumulator to get the cumulative UTXO-set hash and totals
+    // for each row (this ordering is inherently serial, unlike the totals
+    // above). Cheap enough that it's always redone in full, over every
+    // height from `start`, rather than resumed from a checkpoint.
+    raw_results.sort_by_key(|r| r.height);
+
+    // The chain is always seeded from the all-zero predecessor, so
+    // `FilterRecord::header` only chains back to the real BIP157 genesis
+    // predecessor when `start == 0`; for any other `start` it's a
+    // range-relative chain that won't validate against network/Core filter
+    // headers (see the doc comment on `FilterRecord::header`).
+    if emit_filters && start != 0 {
+        warn!(
+            "--start {} != 0: emitted filter headers are range-relative and will not validate against the canonical chain",
+            start
+        );
+    }
+
+    let mut coinstats = CoinStatsAccumulator::new();
+    let mut prev_filter_header = [0u8; 32];
+    let mut final_results = Vec::with_capacity(raw_results.len());
+    let mut filter_records = Vec::new();
+
+    for raw in raw_results {
+        let snapshot = coinstats.apply(&raw.coin_delta);
+
+        let filter_record = raw.filter.as_ref().map(|filter| {
+            prev_filter_header = next_filter_header(filter, prev_filter_header);
+            FilterRecord {
+                height: raw.height,
+                filter: hex::encode(filter),
+                header: hex::encode(prev_filter_header),
+            }
+        });
+
+        let result = BlockResult {
+            height: raw.height,
+            total_txs: raw.total_txs,
+            total_inputs: raw.total_inputs,
+            mixed_tx_count: raw.mixed_tx_count,
+            p2pk_count: raw.script_counts.p2pk,
+            p2pkh_count: raw.script_counts.p2pkh,
+            p2sh_count: raw.script_counts.p2sh,
+            p2wpkh_count: raw.script_counts.p2wpkh,
+            p2wsh_count: raw.script_counts.p2wsh,
+            p2tr_count: raw.script_counts.p2tr,
+            multisig_count: raw.script_counts.multisig,
+            op_return_count: raw.script_counts.op_return,
+            unknown_script_count: raw.script_counts.unknown,
+            utxo_set_hash: snapshot.utxo_set_hash,
+            utxo_count: snapshot.utxo_count,
+            total_amount: snapshot.total_amount,
+            total_unspendable_amount: snapshot.total_unspendable_amount,
+            total_subsidy: snapshot.total_subsidy,
+        };
+
+        if let Some(filter_record) = filter_record {
+            filter_records.push(filter_record);
         }
-    });
+        final_results.push(result);
+    }
 
-    // Sort results by height and return
-    let mut final_results = results.lock().unwrap().to_vec();
-    final_results.sort_by_key(|r| r.height);
+    if let Some(last) = final_results.last() {
+        info!(
+            "Cumulative UTXO set as of height {}: {} UTXOs (hash {})",
+            last.height, last.utxo_count, last.utxo_set_hash
+        );
+    }
 
     info!(
-        "Block processing completed in {:.2}s. Analyzed {} blocks.",
+        "Block processing completed in {:.2}s. Analyzed {} blocks ({} newly scanned).",
         start_time.elapsed().as_secs_f32(),
-        final_results.len()
+        final_results.len(),
+        block_heights.len()
     );
 
-    final_results
+    (final_results, filter_records)
 }
 
 fn main() {
@@ -223,13 +422,26 @@ fn main() {
     );
 
     // Process blocks with the specified range and collect results
-    let results = process_blocks(&chainman, args.start, args.end);
+    let (results, filter_records) = process_blocks(
+        &chainman,
+        args.start,
+        args.end,
+        args.emit_filters,
+        args.checkpoint.as_deref(),
+    );
 
     // Write results to CSV
-    if let Err(e) = write_results_to_csv(&results, &args.output) {
-        error!("Error writing CSV file: {}", e);
+    if let Err(e) = write_results(&results, &args.output, args.format) {
+        error!("Error writing output file: {}", e);
         std::process::exit(1);
     }
 
+    if args.emit_filters {
+        if let Err(e) = write_filters_sidecar(&filter_records, &args.filters_output) {
+            error!("Error writing filters file: {}", e);
+            std::process::exit(1);
+        }
+    }
+
     info!("Analysis complete. Results written to {}", args.output);
 }
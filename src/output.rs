@@ -0,0 +1,157 @@
+use log::info;
+use parquet_derive::ParquetRecordWriter;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::BufWriter;
+use std::time::Instant;
+
+/// Per-block metrics collected while walking the chain.
+///
+/// New metrics should be added as additional fields here rather than by
+/// rewriting a hand-rolled header string; every `OutputWriter` picks them up
+/// automatically, through `Serialize` (CSV, JSON, NDJSON) or the
+/// `ParquetRecordWriter` derive (Parquet).
+#[derive(Debug, Clone, Serialize, Deserialize, ParquetRecordWriter)]
+pub struct BlockResult {
+    pub height: i32,
+    pub total_txs: u32,
+    pub total_inputs: u32,
+    pub mixed_tx_count: u32,
+    pub p2pk_count: u32,
+    pub p2pkh_count: u32,
+    pub p2sh_count: u32,
+    pub p2wpkh_count: u32,
+    pub p2wsh_count: u32,
+    pub p2tr_count: u32,
+    pub multisig_count: u32,
+    pub op_return_count: u32,
+    pub unknown_script_count: u32,
+    /// Rolling MuHash3072 UTXO-set commitment as of this height. The
+    /// accumulator starts empty at `--start`, so this (like `utxo_count` and
+    /// `total_amount`, which are clamped at zero) is the full UTXO-set state
+    /// only when the scan starts at height 0; for any other `--start` it
+    /// covers only coins created and spent within the scanned range.
+    pub utxo_set_hash: String,
+    pub utxo_count: u64,
+    pub total_amount: u64,
+    pub total_unspendable_amount: u64,
+    pub total_subsidy: u64,
+}
+
+/// Output formats supported by `--format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    Csv,
+    Json,
+    Ndjson,
+    Parquet,
+}
+
+/// Writes a full set of `BlockResult`s to a file in a particular format.
+pub trait OutputWriter {
+    fn write(&self, results: &[BlockResult], filename: &str) -> std::io::Result<()>;
+}
+
+pub struct CsvWriter;
+
+impl OutputWriter for CsvWriter {
+    fn write(&self, results: &[BlockResult], filename: &str) -> std::io::Result<()> {
+        let mut writer = csv::Writer::from_path(filename)?;
+        for result in results {
+            writer
+                .serialize(result)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        }
+        writer
+            .flush()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
+    }
+}
+
+pub struct JsonWriter;
+
+impl OutputWriter for JsonWriter {
+    fn write(&self, results: &[BlockResult], filename: &str) -> std::io::Result<()> {
+        let file = File::create(filename)?;
+        serde_json::to_writer_pretty(BufWriter::new(file), results)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
+    }
+}
+
+pub struct NdjsonWriter;
+
+impl OutputWriter for NdjsonWriter {
+    fn write(&self, results: &[BlockResult], filename: &str) -> std::io::Result<()> {
+        let file = File::create(filename)?;
+        let mut writer = BufWriter::new(file);
+        for result in results {
+            serde_json::to_writer(&mut writer, result)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+            std::io::Write::write_all(&mut writer, b"\n")?;
+        }
+        Ok(())
+    }
+}
+
+pub struct ParquetWriter;
+
+impl OutputWriter for ParquetWriter {
+    fn write(&self, results: &[BlockResult], filename: &str) -> std::io::Result<()> {
+        use parquet::file::properties::WriterProperties;
+        use parquet::file::writer::SerializedFileWriter;
+        use parquet::record::RecordWriter;
+        use std::sync::Arc;
+
+        let file = File::create(filename)?;
+        let schema = results
+            .as_slice()
+            .schema()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        let props = Arc::new(WriterProperties::builder().build());
+        let mut writer = SerializedFileWriter::new(file, schema, props)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        let mut row_group_writer = writer
+            .next_row_group()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        results
+            .as_slice()
+            .write_to_row_group(&mut row_group_writer)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        row_group_writer
+            .close()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        writer
+            .close()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        Ok(())
+    }
+}
+
+pub fn writer_for(format: OutputFormat) -> Box<dyn OutputWriter> {
+    match format {
+        OutputFormat::Csv => Box::new(CsvWriter),
+        OutputFormat::Json => Box::new(JsonWriter),
+        OutputFormat::Ndjson => Box::new(NdjsonWriter),
+        OutputFormat::Parquet => Box::new(ParquetWriter),
+    }
+}
+
+pub fn write_results(
+    results: &[BlockResult],
+    filename: &str,
+    format: OutputFormat,
+) -> std::io::Result<()> {
+    info!(
+        "Writing {} results to {:?} file: {}",
+        results.len(),
+        format,
+        filename
+    );
+    let start_time = Instant::now();
+    writer_for(format).write(results, filename)?;
+    info!(
+        "Output file written successfully in {:.2}s",
+        start_time.elapsed().as_secs_f32()
+    );
+    Ok(())
+}
@@ -0,0 +1,204 @@
+use bitcoinkernel::ScriptPubkey;
+
+/// Standard output-script templates, matched the same way Bitcoin Core's
+/// script compressor (`solver.cpp`) recognizes well-known scriptPubKeys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptType {
+    P2pk,
+    P2pkh,
+    P2sh,
+    P2wpkh,
+    P2wsh,
+    P2tr,
+    /// Bare `OP_<m> <pubkey>... OP_<n> OP_CHECKMULTISIG`.
+    Multisig,
+    OpReturn,
+    Unknown,
+}
+
+const OP_DUP: u8 = 0x76;
+const OP_HASH160: u8 = 0xa9;
+const OP_EQUALVERIFY: u8 = 0x88;
+const OP_CHECKSIG: u8 = 0xac;
+const OP_EQUAL: u8 = 0x87;
+const OP_RETURN: u8 = 0x6a;
+const OP_CHECKMULTISIG: u8 = 0xae;
+const OP_0: u8 = 0x00;
+const OP_1: u8 = 0x51;
+const OP_16: u8 = 0x60;
+
+fn is_p2pk(b: &[u8]) -> bool {
+    (b.len() == 35 && b[0] == 0x21 && b[34] == OP_CHECKSIG)
+        || (b.len() == 67 && b[0] == 0x41 && b[66] == OP_CHECKSIG)
+}
+
+fn is_p2pkh(b: &[u8]) -> bool {
+    b.len() == 25
+        && b[0] == OP_DUP
+        && b[1] == OP_HASH160
+        && b[2] == 0x14
+        && b[23] == OP_EQUALVERIFY
+        && b[24] == OP_CHECKSIG
+}
+
+fn is_p2sh(b: &[u8]) -> bool {
+    b.len() == 23 && b[0] == OP_HASH160 && b[1] == 0x14 && b[22] == OP_EQUAL
+}
+
+fn is_p2wpkh(b: &[u8]) -> bool {
+    b.len() == 22 && b[0] == OP_0 && b[1] == 0x14
+}
+
+fn is_p2wsh(b: &[u8]) -> bool {
+    b.len() == 34 && b[0] == OP_0 && b[1] == 0x20
+}
+
+fn is_p2tr(b: &[u8]) -> bool {
+    b.len() == 34 && b[0] == OP_1 && b[1] == 0x20
+}
+
+fn is_op_return(b: &[u8]) -> bool {
+    b.first() == Some(&OP_RETURN)
+}
+
+/// Bare multisig is `OP_<m> (<push> <pubkey>){n} OP_<n> OP_CHECKMULTISIG`
+/// with `1 <= m <= n <= 16`.
+fn is_bare_multisig(b: &[u8]) -> bool {
+    if b.len() < 3 || *b.last().unwrap() != OP_CHECKMULTISIG {
+        return false;
+    }
+    let m = b[0];
+    let n = b[b.len() - 2];
+    if !(OP_1..=OP_16).contains(&m) || !(OP_1..=OP_16).contains(&n) || m > n {
+        return false;
+    }
+
+    let n_keys = (n - OP_1 + 1) as usize;
+    let mut pos = 1;
+    for _ in 0..n_keys {
+        match b.get(pos) {
+            Some(0x21) if b.len() >= pos + 1 + 33 => pos += 1 + 33,
+            Some(0x41) if b.len() >= pos + 1 + 65 => pos += 1 + 65,
+            _ => return false,
+        }
+    }
+
+    pos == b.len() - 2
+}
+
+/// Classifies a scriptPubKey by standard output-script template.
+pub fn classify_script(spk: &ScriptPubkey) -> ScriptType {
+    classify_script_bytes(&spk.get())
+}
+
+/// Same classification, operating directly on raw scriptPubKey bytes (e.g.
+/// bytes already extracted for the coinstats MuHash serialization).
+pub fn classify_script_bytes(b: &[u8]) -> ScriptType {
+    if is_p2pkh(b) {
+        ScriptType::P2pkh
+    } else if is_p2sh(b) {
+        ScriptType::P2sh
+    } else if is_p2tr(b) {
+        ScriptType::P2tr
+    } else if is_p2wpkh(b) {
+        ScriptType::P2wpkh
+    } else if is_p2wsh(b) {
+        ScriptType::P2wsh
+    } else if is_p2pk(b) {
+        ScriptType::P2pk
+    } else if is_op_return(b) {
+        ScriptType::OpReturn
+    } else if is_bare_multisig(b) {
+        ScriptType::Multisig
+    } else {
+        ScriptType::Unknown
+    }
+}
+
+/// Per-block tally of output-script types, counted over newly created output
+/// scriptPubKeys (i.e. address-type adoption, not what's being spent).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ScriptTypeCounts {
+    pub p2pk: u32,
+    pub p2pkh: u32,
+    pub p2sh: u32,
+    pub p2wpkh: u32,
+    pub p2wsh: u32,
+    pub p2tr: u32,
+    pub multisig: u32,
+    pub op_return: u32,
+    pub unknown: u32,
+}
+
+impl ScriptTypeCounts {
+    pub fn record(&mut self, script_type: ScriptType) {
+        match script_type {
+            ScriptType::P2pk => self.p2pk += 1,
+            ScriptType::P2pkh => self.p2pkh += 1,
+            ScriptType::P2sh => self.p2sh += 1,
+            ScriptType::P2wpkh => self.p2wpkh += 1,
+            ScriptType::P2wsh => self.p2wsh += 1,
+            ScriptType::P2tr => self.p2tr += 1,
+            ScriptType::Multisig => self.multisig += 1,
+            ScriptType::OpReturn => self.op_return += 1,
+            ScriptType::Unknown => self.unknown += 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_standard_templates() {
+        assert_eq!(
+            classify_script_bytes(&[vec![0x21], vec![0x02; 33], vec![OP_CHECKSIG]].concat()),
+            ScriptType::P2pk
+        );
+        assert_eq!(
+            classify_script_bytes(
+                &[
+                    vec![OP_DUP, OP_HASH160, 0x14],
+                    vec![0x11; 20],
+                    vec![OP_EQUALVERIFY, OP_CHECKSIG],
+                ]
+                .concat()
+            ),
+            ScriptType::P2pkh
+        );
+        assert_eq!(
+            classify_script_bytes(&[vec![OP_HASH160, 0x14], vec![0x22; 20], vec![OP_EQUAL]].concat()),
+            ScriptType::P2sh
+        );
+        assert_eq!(
+            classify_script_bytes(&[vec![OP_0, 0x14], vec![0x33; 20]].concat()),
+            ScriptType::P2wpkh
+        );
+        assert_eq!(
+            classify_script_bytes(&[vec![OP_0, 0x20], vec![0x44; 32]].concat()),
+            ScriptType::P2wsh
+        );
+        assert_eq!(
+            classify_script_bytes(&[vec![OP_1, 0x20], vec![0x55; 32]].concat()),
+            ScriptType::P2tr
+        );
+        assert_eq!(
+            classify_script_bytes(&[vec![OP_RETURN], vec![0x01, 0x02, 0x03]].concat()),
+            ScriptType::OpReturn
+        );
+        assert_eq!(
+            classify_script_bytes(
+                &[
+                    vec![OP_1],
+                    vec![0x21],
+                    vec![0x66; 33],
+                    vec![OP_1, OP_CHECKMULTISIG],
+                ]
+                .concat()
+            ),
+            ScriptType::Multisig
+        );
+        assert_eq!(classify_script_bytes(&[0x51, 0x52, 0x53]), ScriptType::Unknown);
+    }
+}
@@ -0,0 +1,52 @@
+/// Whole-range aggregate metrics. Each field is a plain associative sum, so a
+/// `BlockStats` per block can be folded with `rayon`'s `reduce` to get
+/// range-wide totals without a final serial pass over every block. Notably
+/// excludes the UTXO-set MuHash commitment: that accumulator is already
+/// folded exactly once, serially, by `CoinStatsAccumulator::apply` (per-row
+/// cumulative hashes require that order anyway), so reducing it here again
+/// would just multiply every block's 3072-bit accumulator a second time.
+#[derive(Debug, Clone, Default)]
+pub struct BlockStats {
+    pub blocks_processed: u64,
+    pub total_txs: u64,
+    pub total_inputs: u64,
+    pub mixed_tx_count: u64,
+    pub p2pk_count: u64,
+    pub p2pkh_count: u64,
+    pub p2sh_count: u64,
+    pub p2wpkh_count: u64,
+    pub p2wsh_count: u64,
+    pub p2tr_count: u64,
+    pub multisig_count: u64,
+    pub op_return_count: u64,
+    pub unknown_script_count: u64,
+    pub utxo_count_delta: i64,
+    pub total_amount_delta: i64,
+    pub total_unspendable_amount: u64,
+    pub total_subsidy: u64,
+}
+
+impl BlockStats {
+    /// Combines two disjoint ranges' stats into one. Plain addition, so
+    /// blocks can be folded in any order.
+    pub fn combine(mut self, other: Self) -> Self {
+        self.blocks_processed += other.blocks_processed;
+        self.total_txs += other.total_txs;
+        self.total_inputs += other.total_inputs;
+        self.mixed_tx_count += other.mixed_tx_count;
+        self.p2pk_count += other.p2pk_count;
+        self.p2pkh_count += other.p2pkh_count;
+        self.p2sh_count += other.p2sh_count;
+        self.p2wpkh_count += other.p2wpkh_count;
+        self.p2wsh_count += other.p2wsh_count;
+        self.p2tr_count += other.p2tr_count;
+        self.multisig_count += other.multisig_count;
+        self.op_return_count += other.op_return_count;
+        self.unknown_script_count += other.unknown_script_count;
+        self.utxo_count_delta += other.utxo_count_delta;
+        self.total_amount_delta += other.total_amount_delta;
+        self.total_unspendable_amount += other.total_unspendable_amount;
+        self.total_subsidy += other.total_subsidy;
+        self
+    }
+}